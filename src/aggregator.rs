@@ -0,0 +1,304 @@
+use chrono::prelude::*;
+use chrono::Duration;
+use lazy_regex::regex_captures;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::downloader::open_tick_lines;
+
+/// Price used to feed the OHLC aggregation.
+#[derive(Debug, Clone, Copy)]
+pub enum PriceType {
+    Mid,
+    Bid,
+    Ask,
+}
+
+impl FromStr for PriceType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mid" => Ok(PriceType::Mid),
+            "bid" => Ok(PriceType::Bid),
+            "ask" => Ok(PriceType::Ask),
+            _ => Err(format!("invalid price type: {}", s)),
+        }
+    }
+}
+
+impl PriceType {
+    fn pick(&self, ask: f32, bid: f32) -> f32 {
+        match self {
+            PriceType::Mid => (ask + bid) / 2.0,
+            PriceType::Bid => bid,
+            PriceType::Ask => ask,
+        }
+    }
+}
+
+// 时间周期，固定长度的用chrono::Duration表示，月/年按日历步进，避免DST/月长度漂移
+#[derive(Debug, PartialEq)]
+enum Timeframe {
+    Duration(Duration),
+    Months(i64),
+    Years(i64),
+}
+
+fn parse_timeframe(s: &str) -> Result<Timeframe, String> {
+    let (_, num, unit) = regex_captures!(r"^(\d+)([smhdwMy])$", s)
+        .ok_or_else(|| format!("invalid timeframe: {}", s))?;
+    let n: i64 = num
+        .parse()
+        .map_err(|_| format!("invalid timeframe: {}", s))?;
+    if n <= 0 {
+        return Err(format!("invalid timeframe: {}", s));
+    }
+
+    let tf = match unit {
+        "s" => Timeframe::Duration(Duration::seconds(n)),
+        "m" => Timeframe::Duration(Duration::minutes(n)),
+        "h" => Timeframe::Duration(Duration::hours(n)),
+        "d" => Timeframe::Duration(Duration::days(n)),
+        "w" => Timeframe::Duration(Duration::weeks(n)),
+        "M" => Timeframe::Months(n),
+        "y" => Timeframe::Years(n),
+        _ => unreachable!(),
+    };
+    Ok(tf)
+}
+
+// bucket_start = epoch + floor((t-epoch)/step)*step
+fn bucket_start(dt: DateTime<Utc>, tf: &Timeframe) -> DateTime<Utc> {
+    match tf {
+        Timeframe::Duration(step) => {
+            let step_ms = step.num_milliseconds();
+            let floored = dt.timestamp_millis().div_euclid(step_ms) * step_ms;
+            Utc.timestamp_millis(floored)
+        }
+        Timeframe::Months(n) => {
+            let total_months = (dt.year() as i64 - 1970) * 12 + (dt.month() as i64 - 1);
+            let floored = total_months.div_euclid(*n) * n;
+            let year = 1970 + floored.div_euclid(12);
+            let month = floored.rem_euclid(12) + 1;
+            Utc.ymd(year as i32, month as u32, 1).and_hms(0, 0, 0)
+        }
+        Timeframe::Years(n) => {
+            let floored = (dt.year() as i64 - 1970).div_euclid(*n) * n;
+            Utc.ymd((1970 + floored) as i32, 1, 1).and_hms(0, 0, 0)
+        }
+    }
+}
+
+struct Tick {
+    dt: DateTime<Utc>,
+    ask: f32,
+    bid: f32,
+    ask_vol: f32,
+    bid_vol: f32,
+}
+
+fn parse_tick(line: &str) -> Option<Tick> {
+    let mut parts = line.splitn(5, ',');
+    let dt = parts.next()?;
+    let ask = parts.next()?;
+    let bid = parts.next()?;
+    let ask_vol = parts.next()?;
+    let bid_vol = parts.next()?;
+
+    let dt = NaiveDateTime::parse_from_str(dt.trim(), "%Y-%m-%d %H:%M:%S%.f UTC").ok()?;
+    Some(Tick {
+        dt: DateTime::from_utc(dt, Utc),
+        ask: ask.trim().parse().ok()?,
+        bid: bid.trim().parse().ok()?,
+        ask_vol: ask_vol.trim().parse().ok()?,
+        bid_vol: bid_vol.trim().parse().ok()?,
+    })
+}
+
+struct Candle {
+    dt: DateTime<Utc>,
+    open: f32,
+    high: f32,
+    low: f32,
+    close: f32,
+    volume: f32,
+}
+
+fn write_candle<W: Write>(w: &mut W, candle: &Candle) -> std::io::Result<()> {
+    writeln!(
+        w,
+        "{},{},{},{},{},{}",
+        candle.dt, candle.open, candle.high, candle.low, candle.close, candle.volume
+    )
+}
+
+// 将dt,ask,bid,ask_vol,bid_vol的tick数据(csv/csv.gz/bin均可, 按input扩展名识别)聚合为
+// dt,open,high,low,close,volume的K线csv
+pub fn aggregate(
+    input: &Path,
+    output: &Path,
+    timeframe: &str,
+    price: PriceType,
+) -> std::io::Result<()> {
+    let tf = parse_timeframe(timeframe)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    let lines = open_tick_lines(input)?;
+    let out = std::fs::File::create(output)?;
+    let mut writer = std::io::BufWriter::new(out);
+
+    let mut current: Option<Candle> = None;
+
+    for line in lines {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let tick = match parse_tick(&line) {
+            Some(t) => t,
+            None => continue,
+        };
+
+        let value = price.pick(tick.ask, tick.bid);
+        let bucket = bucket_start(tick.dt, &tf);
+        let volume = tick.ask_vol + tick.bid_vol;
+
+        match &mut current {
+            Some(candle) if candle.dt == bucket => {
+                candle.high = candle.high.max(value);
+                candle.low = candle.low.min(value);
+                candle.close = value;
+                candle.volume += volume;
+            }
+            _ => {
+                if let Some(candle) = current.take() {
+                    write_candle(&mut writer, &candle)?;
+                }
+                current = Some(Candle {
+                    dt: bucket,
+                    open: value,
+                    high: value,
+                    low: value,
+                    close: value,
+                    volume,
+                });
+            }
+        }
+    }
+
+    if let Some(candle) = current {
+        write_candle(&mut writer, &candle)?;
+    }
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timeframe() {
+        assert_eq!(parse_timeframe("15s").unwrap(), Timeframe::Duration(Duration::seconds(15)));
+        assert_eq!(parse_timeframe("1m").unwrap(), Timeframe::Duration(Duration::minutes(1)));
+        assert_eq!(parse_timeframe("1h").unwrap(), Timeframe::Duration(Duration::hours(1)));
+        assert_eq!(parse_timeframe("1d").unwrap(), Timeframe::Duration(Duration::days(1)));
+        assert_eq!(parse_timeframe("1w").unwrap(), Timeframe::Duration(Duration::weeks(1)));
+        assert_eq!(parse_timeframe("3M").unwrap(), Timeframe::Months(3));
+        assert_eq!(parse_timeframe("1y").unwrap(), Timeframe::Years(1));
+        assert!(parse_timeframe("1x").is_err());
+        assert!(parse_timeframe("m").is_err());
+    }
+
+    #[test]
+    fn test_bucket_start_duration_does_not_leak() {
+        let tf = parse_timeframe("1h").unwrap();
+        let start_of_bucket = Utc.ymd(2020, 1, 1).and_hms(10, 0, 0);
+        let mid_of_bucket = Utc.ymd(2020, 1, 1).and_hms(10, 59, 59);
+        let next_bucket = Utc.ymd(2020, 1, 1).and_hms(11, 0, 0);
+
+        assert_eq!(bucket_start(start_of_bucket, &tf), start_of_bucket);
+        assert_eq!(bucket_start(mid_of_bucket, &tf), start_of_bucket);
+        assert_eq!(bucket_start(next_bucket, &tf), next_bucket);
+    }
+
+    #[test]
+    fn test_bucket_start_month_and_year_calendar_steps() {
+        let months = parse_timeframe("1M").unwrap();
+        assert_eq!(
+            bucket_start(Utc.ymd(2021, 2, 15).and_hms(12, 30, 0), &months),
+            Utc.ymd(2021, 2, 1).and_hms(0, 0, 0)
+        );
+
+        let years = parse_timeframe("1y").unwrap();
+        assert_eq!(
+            bucket_start(Utc.ymd(2021, 11, 30).and_hms(23, 59, 59), &years),
+            Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_aggregate_empty_input() {
+        let dir = std::env::temp_dir().join("dukascopy_aggregator_test_empty");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("empty.csv");
+        let output = dir.join("empty_out.csv");
+        std::fs::write(&input, "").unwrap();
+
+        aggregate(&input, &output, "1m", PriceType::Mid).unwrap();
+
+        let content = std::fs::read_to_string(&output).unwrap();
+        assert!(content.is_empty());
+    }
+
+    #[test]
+    fn test_aggregate_decodes_bin_input() {
+        use byteorder::{LittleEndian, WriteBytesExt};
+
+        let dir = std::env::temp_dir().join("dukascopy_aggregator_test_bin");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("ticks.bin");
+        let output = dir.join("bars_from_bin.csv");
+
+        // mirrors downloader::encode_bin's 24-byte layout: epoch ms(i64) + ask/bid*1e5(i32) + vols(f32)
+        let mut buf = Vec::new();
+        let dt = Utc.ymd(2020, 1, 1).and_hms(10, 0, 0);
+        buf.write_i64::<LittleEndian>(dt.timestamp_millis()).unwrap();
+        buf.write_i32::<LittleEndian>(120_000).unwrap();
+        buf.write_i32::<LittleEndian>(100_000).unwrap();
+        buf.write_f32::<LittleEndian>(1.0).unwrap();
+        buf.write_f32::<LittleEndian>(1.0).unwrap();
+        std::fs::write(&input, &buf).unwrap();
+
+        aggregate(&input, &output, "1h", PriceType::Mid).unwrap();
+
+        let content = std::fs::read_to_string(&output).unwrap();
+        assert_eq!(content.lines().count(), 1);
+        assert_eq!(content.lines().next().unwrap(), "2020-01-01 10:00:00 UTC,1.1,1.1,1.1,1.1,2");
+    }
+
+    #[test]
+    fn test_aggregate_merges_ticks_into_bars() {
+        let dir = std::env::temp_dir().join("dukascopy_aggregator_test_bars");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("ticks.csv");
+        let output = dir.join("bars.csv");
+        std::fs::write(
+            &input,
+            "2020-01-01 10:00:00 UTC,1.2,1.0,1,1\n\
+             2020-01-01 10:00:30 UTC,1.4,1.2,1,1\n\
+             2020-01-01 11:00:00 UTC,1.1,0.9,1,1\n",
+        )
+        .unwrap();
+
+        aggregate(&input, &output, "1h", PriceType::Mid).unwrap();
+
+        let content = std::fs::read_to_string(&output).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "2020-01-01 10:00:00 UTC,1.1,1.3,1.1,1.3,4");
+        assert_eq!(lines[1], "2020-01-01 11:00:00 UTC,1,1,1,1,2");
+    }
+}
@@ -2,20 +2,194 @@ use byteorder::*;
 use chrono::prelude::*;
 use chrono::Duration;
 use colored::Colorize;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use futures::stream::StreamExt;
 use isahc::prelude::*;
 use isahc::AsyncBody;
 use isahc::Response;
 use lazy_regex::regex_captures;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io::BufRead;
 use std::io::Cursor;
+use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration as StdDuration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
 
 use crate::meta::InstrumentMeta;
 
+// bin格式下ask/bid的定点缩放精度，5位小数足以覆盖外汇报价
+const PRICE_SCALE: f32 = 100_000.0;
+
+/// Encoding used for the per-hour tick files and the merged output file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputFormat {
+    Csv,
+    CsvGz,
+    Bin,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(OutputFormat::Csv),
+            "csv.gz" | "gz" => Ok(OutputFormat::CsvGz),
+            "bin" => Ok(OutputFormat::Bin),
+            _ => Err(format!("invalid output format: {}", s)),
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::CsvGz => "csv.gz",
+            OutputFormat::Bin => "bin",
+        }
+    }
+}
+
+const BACKOFF_BASE: StdDuration = StdDuration::from_secs(1);
+const BACKOFF_CEILING: StdDuration = StdDuration::from_secs(60);
+
+// 令牌桶限速器：capacity等于每秒请求数上限，允许短暂突发，超发后排队等待补充
+struct TokenBucket {
+    rate_per_sec: f64,
+    capacity: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn new(rate_per_sec: f64) -> Self {
+        let capacity = rate_per_sec.max(1.0);
+        Self {
+            rate_per_sec: rate_per_sec.max(0.001),
+            capacity,
+            state: Mutex::new((capacity, Instant::now())),
+        }
+    }
+
+    async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                let now = Instant::now();
+                let elapsed = now.duration_since(state.1).as_secs_f64();
+                state.0 = (state.0 + elapsed * self.rate_per_sec).min(self.capacity);
+                state.1 = now;
+
+                if state.0 >= 1.0 {
+                    state.0 -= 1.0;
+                    None
+                } else {
+                    Some(StdDuration::from_secs_f64(
+                        (1.0 - state.0) / self.rate_per_sec,
+                    ))
+                }
+            };
+
+            match wait {
+                None => return,
+                Some(duration) => tokio::time::sleep(duration).await,
+            }
+        }
+    }
+}
+
+// 小幅随机抖动，避免大批量失败URL在同一时刻重试造成新的突发
+fn jitter(max: StdDuration) -> StdDuration {
+    if max.is_zero() {
+        return StdDuration::ZERO;
+    }
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .subsec_nanos() as u64;
+    StdDuration::from_nanos(nanos % (max.as_nanos() as u64).max(1))
+}
+
+// base * 2^(attempt-1)，封顶BACKOFF_CEILING，叠加抖动
+fn backoff_delay(attempt: u16) -> StdDuration {
+    let exp = BACKOFF_BASE.as_secs_f64() * 2f64.powi(attempt.saturating_sub(1) as i32);
+    let capped = exp.min(BACKOFF_CEILING.as_secs_f64());
+    StdDuration::from_secs_f64(capped) + jitter(BACKOFF_BASE)
+}
+
+// filename -> size + sha256, persisted alongside the downloaded files so interrupted
+// runs can be resumed and corrupt files can be singled out for re-download
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileInfo {
+    pub size: u64,
+    pub sha256: String,
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    format!("{:x}", hasher.finalize())
+}
+
+fn manifest_path(dir: &Path) -> PathBuf {
+    dir.join("manifest.json")
+}
+
+fn load_manifest(dir: &Path) -> HashMap<String, FileInfo> {
+    std::fs::read_to_string(manifest_path(dir))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_manifest(dir: &Path, manifest: &HashMap<String, FileInfo>) {
+    if let Ok(json) = serde_json::to_string_pretty(manifest) {
+        let _ = std::fs::write(manifest_path(dir), json);
+    }
+}
+
+fn format_marker_path(dir: &Path) -> PathBuf {
+    dir.join("format.json")
+}
+
+// per-hour文件实际写入时使用的编码, 记在symbol目录下供merge校验,
+// 避免用错误的编码假设去解析/拼接文件而静默产生损坏的合并结果
+fn save_download_format(dir: &Path, format: OutputFormat) {
+    if let Ok(json) = serde_json::to_string(&format) {
+        let _ = std::fs::write(format_marker_path(dir), json);
+    }
+}
+
+pub fn load_download_format(dir: &Path) -> Option<OutputFormat> {
+    let content = std::fs::read_to_string(format_marker_path(dir)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+// 与manifest记录不一致(缺失/大小不符/哈希不符)的文件视为损坏，需要重新下载
+fn find_corrupt_files(dir: &Path, manifest: &HashMap<String, FileInfo>) -> HashSet<String> {
+    let mut corrupt = HashSet::new();
+    for (filename, info) in manifest {
+        let bytes = std::fs::read(dir.join(filename));
+        match bytes {
+            Ok(bytes) if bytes.len() as u64 == info.size && sha256_hex(&bytes) == info.sha256 => {}
+            _ => {
+                corrupt.insert(filename.clone());
+            }
+        }
+    }
+    corrupt
+}
+
 #[derive(Debug)]
 pub struct Record {
     dt: DateTime<Utc>,
@@ -108,8 +282,8 @@ fn decode_url(url: &str) -> UrlInfo {
     }
 }
 
-async fn write_to_file(info: &UrlInfo, records: &Vec<Record>, path: &Path) -> std::io::Result<()> {
-    let filename = format!(
+fn output_file_name(info: &UrlInfo) -> String {
+    format!(
         "{}_{}_{:0>width$}_{:0>width$}_{:0>width$}h_ticks.bi5",
         info.symbol,
         info.year,
@@ -117,27 +291,94 @@ async fn write_to_file(info: &UrlInfo, records: &Vec<Record>, path: &Path) -> st
         info.day,
         info.hour,
         width = 2
-    );
+    )
+}
 
-    let mut path_buf = path.to_path_buf();
-    path_buf.push(filename);
+fn format_record_csv(r: &Record) -> String {
+    format!("{},{},{},{},{}", r.dt, r.ask, r.bid, r.ask_vol, r.bid_vol)
+}
 
-    let mut csv = fs::File::create(path_buf.as_path()).await?;
-    let content = records
+fn encode_csv(records: &[Record]) -> Vec<u8> {
+    records
         .iter()
-        .map(|r| format!("{},{},{},{},{}", r.dt, r.ask, r.bid, r.ask_vol, r.bid_vol))
+        .map(format_record_csv)
         .collect::<Vec<String>>()
-        .join("\n");
-    csv.write_all(content.as_bytes()).await?;
-    csv.flush().await
+        .join("\n")
+        .into_bytes()
+}
+
+// 24字节定长: epoch ms(i64) + ask/bid定点(i32) + ask_vol/bid_vol(f32)
+// 用绝对时间戳而非小时内偏移, 这样多个per-hour文件拼接后每条记录仍能独立定位到其所属的小时/天
+const BIN_RECORD_SIZE: usize = 24;
+
+fn encode_bin(records: &[Record]) -> std::io::Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(records.len() * BIN_RECORD_SIZE);
+    for r in records {
+        WriteBytesExt::write_i64::<LittleEndian>(&mut buf, r.dt.timestamp_millis())?;
+        WriteBytesExt::write_i32::<LittleEndian>(&mut buf, (r.ask * PRICE_SCALE).round() as i32)?;
+        WriteBytesExt::write_i32::<LittleEndian>(&mut buf, (r.bid * PRICE_SCALE).round() as i32)?;
+        WriteBytesExt::write_f32::<LittleEndian>(&mut buf, r.ask_vol)?;
+        WriteBytesExt::write_f32::<LittleEndian>(&mut buf, r.bid_vol)?;
+    }
+    Ok(buf)
 }
 
+// encode_bin的逆过程, 供merge后的文件被aggregator/range读回时解码单条记录
+fn decode_bin_record(bytes: &[u8]) -> std::io::Result<Record> {
+    let mut cursor = Cursor::new(bytes);
+    let ms = cursor.read_i64::<LittleEndian>()?;
+    let ask = cursor.read_i32::<LittleEndian>()? as f32 / PRICE_SCALE;
+    let bid = cursor.read_i32::<LittleEndian>()? as f32 / PRICE_SCALE;
+    let ask_vol = cursor.read_f32::<LittleEndian>()?;
+    let bid_vol = cursor.read_f32::<LittleEndian>()?;
+    Ok(Record::new(Utc.timestamp_millis(ms), ask, bid, ask_vol, bid_vol))
+}
+
+fn encode_records(records: &[Record], format: OutputFormat) -> std::io::Result<Vec<u8>> {
+    match format {
+        OutputFormat::Csv => Ok(encode_csv(records)),
+        OutputFormat::CsvGz => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&encode_csv(records))?;
+            encoder.finish()
+        }
+        OutputFormat::Bin => encode_bin(records),
+    }
+}
+
+async fn write_to_file(
+    info: &UrlInfo,
+    records: &Vec<Record>,
+    path: &Path,
+    format: OutputFormat,
+) -> std::io::Result<FileInfo> {
+    let filename = output_file_name(info);
+
+    let mut path_buf = path.to_path_buf();
+    path_buf.push(filename);
+
+    let bytes = encode_records(records, format)?;
+
+    let mut file = fs::File::create(path_buf.as_path()).await?;
+    file.write_all(&bytes).await?;
+    file.flush().await?;
+
+    Ok(FileInfo {
+        size: bytes.len() as u64,
+        sha256: sha256_hex(&bytes),
+    })
+}
+
+// 即便该小时没有tick(404或200空body, 周末/假期很常见)也会写出一个空的占位文件并计入manifest,
+// 这样resume时该小时已"处理过", 不会被反复重新请求
 async fn process_response(
     url: &str,
     mut response: Response<AsyncBody>,
     path: &Path,
     meta_dict: &HashMap<String, InstrumentMeta>,
-) -> std::io::Result<()> {
+    format: OutputFormat,
+) -> std::io::Result<Option<(String, FileInfo)>> {
+    let info = decode_url(url);
     let mut records: Vec<Record> = Vec::new();
 
     if response.status() == 200 && response.body().len().unwrap() != 0 {
@@ -147,7 +388,6 @@ async fn process_response(
         let mut decomp: Vec<u8> = Vec::new();
         lzma_rs::lzma_decompress(&mut buf.as_slice(), &mut decomp).unwrap();
 
-        let info = decode_url(url);
         let meta_info = &meta_dict[&info.symbol];
 
         let decomp_len = decomp.len();
@@ -168,51 +408,67 @@ async fn process_response(
             records.push(Record::new(dt, ask, bid, ask_vol, bid_vol));
             pos += 20;
         }
-
-        write_to_file(&info, &records, path).await?
     }
-    Ok(())
+
+    let file_info = write_to_file(&info, &records, path, format).await?;
+    Ok(Some((output_file_name(&info), file_info)))
 }
 
-// 返回出错的URL
+// 返回(出错的URL, 成功写入的文件清单)
 async fn download_urls(
     meta_dict: &HashMap<String, InstrumentMeta>,
     urls: Vec<String>,
     path: &Path,
     verbose: bool,
-) -> Vec<String> {
-    let fetches = futures::stream::iter(urls.into_iter().map(|url| async move {
-        let backup_url = url.clone();
-        let response = isahc::get_async(url).await;
-        match response {
-            Ok(resp) => {
-                if verbose {
-                    println!("{} --> {}", backup_url, resp.status());
+    rate_limit: f64,
+    max_concurrency: usize,
+    format: OutputFormat,
+) -> (Vec<String>, Vec<(String, FileInfo)>) {
+    let bucket = Arc::new(TokenBucket::new(rate_limit));
+
+    let fetches = futures::stream::iter(urls.into_iter().map(|url| {
+        let bucket = Arc::clone(&bucket);
+        async move {
+            let backup_url = url.clone();
+            bucket.acquire().await;
+            let response = isahc::get_async(url).await;
+            match response {
+                Ok(resp) => {
+                    if verbose {
+                        println!("{} --> {}", backup_url, resp.status());
+                    }
+                    if resp.status() == 200 || resp.status() == 404 {
+                        let entry = process_response(&backup_url, resp, path, meta_dict, format)
+                            .await
+                            .unwrap_or(None);
+                        Ok(entry)
+                    } else {
+                        Err(backup_url)
+                    }
                 }
-                if resp.status() == 200 || resp.status() == 404 {
-                    let _ = process_response(&backup_url, resp, path, meta_dict).await;
-                    None
-                } else {
-                    Some(backup_url)
+                Err(e) => {
+                    if verbose {
+                        println!("{} --> {}", backup_url.red(), e);
+                    }
+                    Err(backup_url)
                 }
             }
-            Err(e) => {
-                if verbose {
-                    println!("{} --> {}", backup_url.red(), e);
-                }
-                Some(backup_url)
-            }
         }
     }))
-    .buffered(24)
-    .collect::<Vec<Option<String>>>();
-
-    fetches
-        .await
-        .into_iter()
-        .filter(|value| value.is_some())
-        .map(|v| v.unwrap())
-        .collect::<Vec<String>>()
+    .buffer_unordered(max_concurrency.max(1))
+    .collect::<Vec<Result<Option<(String, FileInfo)>, String>>>();
+
+    let mut error_urls: Vec<String> = Vec::new();
+    let mut entries: Vec<(String, FileInfo)> = Vec::new();
+    for result in fetches.await {
+        match result {
+            Ok(Some(entry)) => entries.push(entry),
+            Ok(None) => {}
+            Err(url) => error_urls.push(url),
+        }
+    }
+
+    (error_urls, entries)
 }
 
 // 返回出错的URL
@@ -224,6 +480,11 @@ pub async fn download(
     end: Date<Utc>,
     retry_count: u16,
     verbose: bool,
+    force: bool,
+    verify: bool,
+    rate_limit: f64,
+    max_concurrency: usize,
+    format: OutputFormat,
 ) -> std::io::Result<Vec<String>> {
     let mut path_buf = output.clone();
     path_buf.push(symbol);
@@ -241,17 +502,67 @@ pub async fn download(
         path_buf.as_path().to_str().unwrap().yellow()
     );
 
-    let urls = build_urls(&symbol, start, end);
-    let mut error_urls = download_urls(meta_dict, urls, path_buf.as_path(), verbose).await;
+    let mut manifest = load_manifest(path_buf.as_path());
+    let corrupt = if verify {
+        find_corrupt_files(path_buf.as_path(), &manifest)
+    } else {
+        HashSet::new()
+    };
+
+    let mut urls = build_urls(&symbol, start, end);
+    if !force {
+        urls.retain(|url| {
+            let filename = output_file_name(&decode_url(url));
+            // 空的占位文件(无tick的小时)也算已处理, 只看文件是否存在而非大小
+            let up_to_date = path_buf.join(&filename).metadata().is_ok();
+            !up_to_date || corrupt.contains(&filename)
+        });
+    }
+
+    let (mut error_urls, entries) = download_urls(
+        meta_dict,
+        urls,
+        path_buf.as_path(),
+        verbose,
+        rate_limit,
+        max_concurrency,
+        format,
+    )
+    .await;
+    manifest.extend(entries);
 
     let mut index = 1;
     while error_urls.len() > 0 && index <= retry_count {
-        println!("{}", format!("Retry({}/{})", index, retry_count).yellow());
-        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-        error_urls = download_urls(meta_dict, error_urls, path_buf.as_path(), verbose).await;
+        let delay = backoff_delay(index);
+        println!(
+            "{}",
+            format!(
+                "Retry({}/{}) after {:.1}s",
+                index,
+                retry_count,
+                delay.as_secs_f64()
+            )
+            .yellow()
+        );
+        tokio::time::sleep(delay).await;
+        let (errs, entries) = download_urls(
+            meta_dict,
+            error_urls,
+            path_buf.as_path(),
+            verbose,
+            rate_limit,
+            max_concurrency,
+            format,
+        )
+        .await;
+        error_urls = errs;
+        manifest.extend(entries);
         index += 1;
     }
 
+    save_manifest(path_buf.as_path(), &manifest);
+    save_download_format(path_buf.as_path(), format);
+
     if error_urls.len() > 0 {
         println!("{} fetch urls = {:?}", "Error".red(), error_urls);
     } else {
@@ -261,6 +572,83 @@ pub async fn download(
     Ok(error_urls)
 }
 
+// 按format合并一组per-hour文件: csv逐个拼接并在每个文件后补一个换行, bin直接拼接字节, csv.gz则先解出明文再整体重新压缩
+pub fn merge_files(format: OutputFormat, inputs: &[PathBuf], output: &Path) -> std::io::Result<()> {
+    let out_file = std::fs::File::create(output)?;
+
+    match format {
+        OutputFormat::Csv => {
+            let mut writer = out_file;
+            for path in inputs {
+                let mut file = std::fs::File::open(path)?;
+                std::io::copy(&mut file, &mut writer)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        OutputFormat::CsvGz => {
+            let mut encoder = GzEncoder::new(out_file, Compression::default());
+            for path in inputs {
+                let file = std::fs::File::open(path)?;
+                let mut decoder = GzDecoder::new(file);
+                std::io::copy(&mut decoder, &mut encoder)?;
+                encoder.write_all(b"\n")?;
+            }
+            encoder.finish()?;
+        }
+        OutputFormat::Bin => {
+            let mut writer = out_file;
+            for path in inputs {
+                let mut file = std::fs::File::open(path)?;
+                std::io::copy(&mut file, &mut writer)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// 定长bin记录的逐行迭代器: 每次读取BIN_RECORD_SIZE字节并解码成一行csv文本, 供aggregator/range复用
+struct BinLines {
+    reader: std::io::BufReader<std::fs::File>,
+}
+
+impl Iterator for BinLines {
+    type Item = std::io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buf = [0u8; BIN_RECORD_SIZE];
+        match std::io::Read::read_exact(&mut self.reader, &mut buf) {
+            Ok(()) => Some(decode_bin_record(&buf).map(|r| format_record_csv(&r))),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+// 按扩展名识别编码并统一成逐行文本: csv按原样读, csv.gz解压读, bin按定长记录解码成csv文本行
+// aggregator/range只关心文本行, 借此对三种per-hour/merge编码保持透明
+pub(crate) fn open_tick_lines(
+    path: &Path,
+) -> std::io::Result<Box<dyn Iterator<Item = std::io::Result<String>>>> {
+    let is_bin = path.extension().and_then(|e| e.to_str()) == Some("bin");
+    let is_gz = path.extension().and_then(|e| e.to_str()) == Some("gz");
+
+    if is_bin {
+        let file = std::fs::File::open(path)?;
+        Ok(Box::new(BinLines {
+            reader: std::io::BufReader::new(file),
+        }))
+    } else if is_gz {
+        let file = std::fs::File::open(path)?;
+        let reader = std::io::BufReader::new(GzDecoder::new(file));
+        Ok(Box::new(BufRead::lines(reader)))
+    } else {
+        let file = std::fs::File::open(path)?;
+        let reader = std::io::BufReader::new(file);
+        Ok(Box::new(BufRead::lines(reader)))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::TimeZone;
@@ -298,6 +686,118 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_output_format_from_str() {
+        assert_eq!("csv".parse::<OutputFormat>().unwrap(), OutputFormat::Csv);
+        assert_eq!(
+            "csv.gz".parse::<OutputFormat>().unwrap(),
+            OutputFormat::CsvGz
+        );
+        assert_eq!("BIN".parse::<OutputFormat>().unwrap(), OutputFormat::Bin);
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn test_encode_bin_round_trip() {
+        let dt = Utc.ymd(2020, 1, 1).and_hms(10, 0, 0) + Duration::milliseconds(1500);
+        let records = vec![Record::new(dt, 1.2345, 1.2343, 10.0, 20.0)];
+
+        let bytes = encode_bin(&records).unwrap();
+        assert_eq!(bytes.len(), BIN_RECORD_SIZE);
+
+        let mut cursor = Cursor::new(bytes);
+        assert_eq!(cursor.read_i64::<LittleEndian>().unwrap(), dt.timestamp_millis());
+        assert_eq!(cursor.read_i32::<LittleEndian>().unwrap(), 123450);
+        assert_eq!(cursor.read_i32::<LittleEndian>().unwrap(), 123430);
+        assert_eq!(cursor.read_f32::<LittleEndian>().unwrap(), 10.0);
+        assert_eq!(cursor.read_f32::<LittleEndian>().unwrap(), 20.0);
+    }
+
+    #[test]
+    fn test_encode_bin_preserves_hour_across_concatenation() {
+        // merge_files concatenates per-hour bin files as raw bytes; each record must carry
+        // enough information (absolute epoch ms) to recover which hour it belongs to.
+        let dt_10h = Utc.ymd(2020, 1, 1).and_hms(10, 0, 0) + Duration::milliseconds(1500);
+        let dt_11h = Utc.ymd(2020, 1, 1).and_hms(11, 0, 0) + Duration::milliseconds(1500);
+
+        let mut merged = encode_bin(&[Record::new(dt_10h, 1.0, 1.0, 1.0, 1.0)]).unwrap();
+        merged.extend(encode_bin(&[Record::new(dt_11h, 1.0, 1.0, 1.0, 1.0)]).unwrap());
+
+        let first = decode_bin_record(&merged[0..BIN_RECORD_SIZE]).unwrap();
+        let second = decode_bin_record(&merged[BIN_RECORD_SIZE..2 * BIN_RECORD_SIZE]).unwrap();
+        assert_eq!(first.dt, dt_10h);
+        assert_eq!(second.dt, dt_11h);
+        assert_ne!(first.dt, second.dt);
+    }
+
+    #[test]
+    fn test_backoff_delay_grows_and_caps() {
+        let first = backoff_delay(1);
+        let second = backoff_delay(2);
+        let capped = backoff_delay(20);
+
+        assert!(first.as_secs_f64() < second.as_secs_f64());
+        assert!(capped.as_secs_f64() <= BACKOFF_CEILING.as_secs_f64() + BACKOFF_BASE.as_secs_f64());
+    }
+
+    #[test]
+    fn test_output_file_name() {
+        let dt = Utc.ymd(2003, 1, 5);
+        let info = decode_url(&build_day_urls("EURUSD", dt)[3]);
+        assert_eq!(output_file_name(&info), "EURUSD_2003_01_05_03h_ticks.bi5");
+    }
+
+    #[test]
+    fn test_find_corrupt_files() {
+        let dir = std::env::temp_dir().join("dukascopy_manifest_test");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        std::fs::write(dir.join("ok.bi5"), b"hello").unwrap();
+        std::fs::write(dir.join("tampered.bi5"), b"tampered content").unwrap();
+
+        let mut manifest = HashMap::new();
+        manifest.insert(
+            "ok.bi5".to_string(),
+            FileInfo {
+                size: 5,
+                sha256: sha256_hex(b"hello"),
+            },
+        );
+        manifest.insert(
+            "tampered.bi5".to_string(),
+            FileInfo {
+                size: 5,
+                sha256: sha256_hex(b"hello"),
+            },
+        );
+        manifest.insert(
+            "missing.bi5".to_string(),
+            FileInfo {
+                size: 5,
+                sha256: sha256_hex(b"hello"),
+            },
+        );
+
+        let corrupt = find_corrupt_files(&dir, &manifest);
+        assert!(!corrupt.contains("ok.bi5"));
+        assert!(corrupt.contains("tampered.bi5"));
+        assert!(corrupt.contains("missing.bi5"));
+    }
+
+    #[test]
+    fn test_download_format_round_trip() {
+        let dir = std::env::temp_dir().join("dukascopy_format_marker_test");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        assert!(load_download_format(&dir).is_none());
+
+        save_download_format(&dir, OutputFormat::Bin);
+        assert_eq!(load_download_format(&dir), Some(OutputFormat::Bin));
+
+        save_download_format(&dir, OutputFormat::Csv);
+        assert_eq!(load_download_format(&dir), Some(OutputFormat::Csv));
+    }
+
     #[test]
     fn test_url_info() {
         let dt = Utc.ymd(2003, 1, 5);
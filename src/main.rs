@@ -1,10 +1,11 @@
+mod aggregator;
 mod downloader;
 mod meta;
+mod range;
 use chrono::prelude::*;
 use chrono::Duration;
 use colored::Colorize;
 use meta::download_meta_info;
-use std::io::Write;
 use std::path::PathBuf;
 use structopt::StructOpt;
 
@@ -25,6 +26,9 @@ enum Opt {
 
     /// Aggregator Tick Data To Candle
     Aggregator(AggregatorOptions),
+
+    /// Extract A Time Range From A Merged Csv
+    Range(RangeOptions),
 }
 
 #[derive(StructOpt, Debug)]
@@ -40,6 +44,14 @@ pub struct MetaOptions {
     /// Retry Count
     #[structopt(short, long, default_value = "3")]
     retry_count: u16,
+
+    /// Bypass the local instruments cache and force a fetch
+    #[structopt(long)]
+    refresh_meta: bool,
+
+    /// Instruments cache lifetime in hours before it's considered stale
+    #[structopt(long, default_value = "24")]
+    cache_ttl: u64,
 }
 
 #[derive(StructOpt, Debug)]
@@ -67,6 +79,34 @@ struct DownloadOptions {
     /// Retry Count
     #[structopt(short, long, default_value = "10")]
     retry_count: u16,
+
+    /// Bypass the local instruments cache and force a fetch
+    #[structopt(long)]
+    refresh_meta: bool,
+
+    /// Instruments cache lifetime in hours before it's considered stale
+    #[structopt(long, default_value = "24")]
+    cache_ttl: u64,
+
+    /// Re-download every hour even if its output file already exists
+    #[structopt(long)]
+    force: bool,
+
+    /// Re-hash existing files against the manifest and re-download the corrupt ones
+    #[structopt(long)]
+    verify: bool,
+
+    /// Max requests per second fed into the download pipeline
+    #[structopt(long, default_value = "20")]
+    rate_limit: f64,
+
+    /// Max number of in-flight requests
+    #[structopt(long, default_value = "24")]
+    max_concurrency: usize,
+
+    /// Per-hour tick file encoding: csv, csv.gz or bin
+    #[structopt(long, default_value = "csv")]
+    format: downloader::OutputFormat,
 }
 
 #[derive(StructOpt, Debug)]
@@ -86,6 +126,10 @@ struct MergeOptions {
     /// Symbols like EURUSD GBPUSD, split by whitespace
     #[structopt(name = "SYMBOLS")]
     symbols: Vec<String>,
+
+    /// Merged output file encoding: csv, csv.gz or bin
+    #[structopt(long, default_value = "csv")]
+    format: downloader::OutputFormat,
 }
 
 #[derive(StructOpt, Debug)]
@@ -102,14 +146,55 @@ struct AggregatorOptions {
     #[structopt(short, long, parse(from_os_str))]
     output: PathBuf,
 
-    /// Aggregator Timeframe, like 15s 1m 1h 1d 1w 1m 1y
+    /// Aggregator Timeframe, like 15s 1m 1h 1d 1w 1M 1y
     #[structopt(short, long)]
     timeframe: String,
+
+    /// Price used to build OHLC: mid, bid or ask
+    #[structopt(long, default_value = "mid")]
+    price: aggregator::PriceType,
+}
+
+// RFC3339表示的时间点或YYYY-MM-DD的日期(视为当天00:00:00 UTC)
+fn parse_datetime(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Ok(dt.with_timezone(&Utc));
+    }
+    s.parse::<NaiveDate>()
+        .map(|nd| Date::<Utc>::from_utc(nd, Utc).and_hms(0, 0, 0))
+        .map_err(|_| format!("invalid date/time: {}", s))
+}
+
+#[derive(StructOpt, Debug)]
+struct RangeOptions {
+    /// Verbose mode
+    #[structopt(short, long)]
+    verbose: bool,
+
+    /// Source Merged Csv File
+    #[structopt(short, long, parse(from_os_str))]
+    input: PathBuf,
+
+    /// Output Csv File
+    #[structopt(short, long, parse(from_os_str))]
+    output: PathBuf,
+
+    /// Symbol this file belongs to, like EURUSD
+    #[structopt(short, long)]
+    symbol: String,
+
+    /// Range Start, RFC3339 or YYYY-MM-DD, inclusive
+    #[structopt(long, parse(try_from_str = parse_datetime))]
+    start: DateTime<Utc>,
+
+    /// Range End, RFC3339 or YYYY-MM-DD, exclusive
+    #[structopt(long, parse(try_from_str = parse_datetime))]
+    end: DateTime<Utc>,
 }
 
 async fn command_download(opt: &DownloadOptions) -> std::io::Result<()> {
     print!("{} instruments meta info...", "Fetching".yellow());
-    let meta_dict = meta::build_meta_info();
+    let meta_dict = meta::build_meta_info(opt.refresh_meta, opt.cache_ttl);
 
     if meta_dict.len() > 0 {
         println!("{}", "Done".green());
@@ -160,6 +245,11 @@ async fn command_download(opt: &DownloadOptions) -> std::io::Result<()> {
             end,
             opt.retry_count,
             opt.verbose,
+            opt.force,
+            opt.verify,
+            opt.rate_limit,
+            opt.max_concurrency,
+            opt.format,
         )
         .await?;
     }
@@ -173,16 +263,33 @@ fn command_merge(opt: &MergeOptions) -> std::io::Result<()> {
         }
 
         let mut output_path = opt.output.clone();
-        output_path.push(format!("{}.csv", symbol.to_uppercase()));
-        let mut csv_file = std::fs::File::create(&output_path)?;
+        output_path.push(format!("{}.{}", symbol.to_uppercase(), opt.format.extension()));
         let mut bi5_files: Vec<PathBuf> = Vec::new();
 
         let mut input_path = opt.input.clone();
         input_path.push(symbol.to_uppercase());
+
+        // per-hour文件实际编码由下载时记录的format.json决定；与--format不一致就报错并跳过该symbol，
+        // 避免merge_files按错误假设解析/拼接, 静默产出一个内容损坏的输出文件
+        if let Some(downloaded_format) = downloader::load_download_format(&input_path) {
+            if downloaded_format != opt.format {
+                println!(
+                    "{} {} was downloaded as {} but --format is {}; re-run merge with --format {}",
+                    "Error".red(),
+                    symbol.cyan(),
+                    downloaded_format.extension(),
+                    opt.format.extension(),
+                    downloaded_format.extension()
+                );
+                continue;
+            }
+        }
+
         for entry in std::fs::read_dir(&input_path)? {
             let entry = entry?;
             let path = entry.path();
-            if path.is_file() {
+            let is_tick_file = path.extension().and_then(|e| e.to_str()) == Some("bi5");
+            if path.is_file() && is_tick_file {
                 bi5_files.push(path);
             }
         }
@@ -191,15 +298,13 @@ fn command_merge(opt: &MergeOptions) -> std::io::Result<()> {
 
         println!("{} {} files", "Find".yellow(), bi5_files.len());
 
-        for path in &bi5_files {
-            if opt.verbose {
+        if opt.verbose {
+            for path in &bi5_files {
                 println!("{} {}", "Reading".yellow(), path.to_str().unwrap());
             }
-            let mut file = std::fs::File::open(path)?;
-            std::io::copy(&mut file, &mut csv_file)?;
-            csv_file.write("\n".as_bytes())?;
         }
-        csv_file.flush()?;
+
+        downloader::merge_files(opt.format, &bi5_files, &output_path)?;
 
         println!(
             "{} {} {}",
@@ -213,7 +318,29 @@ fn command_merge(opt: &MergeOptions) -> std::io::Result<()> {
 }
 
 fn command_aggregator(opt: &AggregatorOptions) -> std::io::Result<()> {
-    Ok(())
+    if opt.verbose {
+        println!(
+            "{} {} timeframe:{} price:{:?}",
+            "Aggregating".yellow(),
+            opt.input.to_str().unwrap(),
+            opt.timeframe,
+            opt.price
+        );
+    }
+    aggregator::aggregate(&opt.input, &opt.output, &opt.timeframe, opt.price)
+}
+
+fn command_range(opt: &RangeOptions) -> std::io::Result<()> {
+    if opt.verbose {
+        println!(
+            "{} {} from {} to {}",
+            "Extracting".yellow(),
+            opt.symbol.cyan(),
+            opt.start,
+            opt.end
+        );
+    }
+    range::extract(&opt.input, &opt.output, opt.start, opt.end)
 }
 
 fn command_meta(opt: &MetaOptions) -> std::io::Result<()> {
@@ -234,11 +361,18 @@ async fn main() -> std::io::Result<()> {
             let _ = command_merge(&opt);
         }
         Opt::Aggregator(opt) => {
-            let _ = command_aggregator(&opt);
+            if let Err(e) = command_aggregator(&opt) {
+                println!("{} {}", "Error".red(), e);
+            }
         }
         Opt::Meta(opt) => {
             let _ = command_meta(&opt);
         }
+        Opt::Range(opt) => {
+            if let Err(e) = command_range(&opt) {
+                println!("{} {}", "Error".red(), e);
+            }
+        }
     }
     Ok(())
 }
@@ -1,10 +1,12 @@
 use chrono::offset::TimeZone;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use colored::Colorize;
 use isahc::{config::Configurable, ReadResponseExt, Request, RequestExt};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
 
 use crate::MetaOptions;
 
@@ -14,14 +16,79 @@ pub struct InstrumentMeta {
     pub history_start_tick: DateTime<Utc>,
 }
 
+fn cache_file_path() -> PathBuf {
+    let home = std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."));
+    home.join(".cache").join("dukascopy").join("instruments.json")
+}
+
+// 读取缓存的原始instruments json，超过ttl_hours视为过期
+fn load_cached_meta_data(path: &Path, ttl_hours: u64) -> Option<String> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let value: Value = serde_json::from_str(&content).ok()?;
+    let fetched_at = value["fetched_at"].as_str()?;
+    let fetched_at = DateTime::parse_from_rfc3339(fetched_at)
+        .ok()?
+        .with_timezone(&Utc);
+
+    if Utc::now() - fetched_at > Duration::hours(ttl_hours as i64) {
+        return None;
+    }
+
+    value["data"].as_str().map(String::from)
+}
+
+fn save_cached_meta_data(path: &Path, data: &str) {
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+
+    let payload = serde_json::json!({
+        "fetched_at": Utc::now().to_rfc3339(),
+        "data": data,
+    });
+    let _ = std::fs::write(path, payload.to_string());
+}
+
+// refresh为true时跳过缓存，否则返回未过期的本地缓存(如果有)；两处"是否可以用缓存"的判断共用这里
+fn cached_unless_refresh(path: &Path, refresh: bool, ttl_hours: u64) -> Option<String> {
+    if refresh {
+        return None;
+    }
+    load_cached_meta_data(path, ttl_hours)
+}
+
+// 优先使用未过期的本地缓存，否则抓取并刷新缓存
+fn fetch_meta_data_cached(refresh: bool, ttl_hours: u64) -> Option<String> {
+    let path = cache_file_path();
+    if let Some(cached) = cached_unless_refresh(&path, refresh, ttl_hours) {
+        return Some(cached);
+    }
+
+    let data = fetch_meta_data()?;
+    save_cached_meta_data(&path, &data);
+    Some(data)
+}
+
 fn download_and_retry(opt: &MetaOptions) -> Option<String> {
     if opt.verbose {
         print!("{} meta info", "Fetching..".yellow());
     }
+
+    let path = cache_file_path();
+    if let Some(cached) = cached_unless_refresh(&path, opt.refresh_meta, opt.cache_ttl) {
+        if opt.verbose {
+            println!("  {}", "Done (cache)".green());
+        }
+        return Some(cached);
+    }
+
     let mut index: u16 = 0;
     while index < opt.retry_count {
         let meta_data = fetch_meta_data();
-        if meta_data.is_some() {
+        if let Some(data) = &meta_data {
+            save_cached_meta_data(&path, data);
             if opt.verbose {
                 println!("  {}", "Done".green());
             }
@@ -65,9 +132,9 @@ pub fn download_meta_info(opt: &MetaOptions) {
     }
 }
 
-pub fn build_meta_info() -> HashMap<String, InstrumentMeta> {
+pub fn build_meta_info(refresh: bool, cache_ttl: u64) -> HashMap<String, InstrumentMeta> {
     let mut info_map = HashMap::new();
-    let meta_data = fetch_meta_data();
+    let meta_data = fetch_meta_data_cached(refresh, cache_ttl);
     if meta_data.is_some() {
         let config = meta_data.as_ref().unwrap();
         let all: Value = serde_json::from_str(&config).unwrap();
@@ -116,3 +183,34 @@ pub fn fetch_meta_data() -> Option<String> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached_meta_data_round_trip() {
+        let path = std::env::temp_dir().join("dukascopy_meta_test_round_trip.json");
+
+        save_cached_meta_data(&path, "{\"instruments\":{}}");
+
+        assert_eq!(
+            load_cached_meta_data(&path, 24).as_deref(),
+            Some("{\"instruments\":{}}")
+        );
+    }
+
+    #[test]
+    fn test_load_cached_meta_data_expired() {
+        let path = std::env::temp_dir().join("dukascopy_meta_test_expired.json");
+        let fetched_at = Utc::now() - Duration::hours(25);
+        let payload = serde_json::json!({
+            "fetched_at": fetched_at.to_rfc3339(),
+            "data": "{\"instruments\":{}}",
+        });
+        std::fs::write(&path, payload.to_string()).unwrap();
+
+        assert!(load_cached_meta_data(&path, 24).is_none());
+        assert!(load_cached_meta_data(&path, 48).is_some());
+    }
+}
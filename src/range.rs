@@ -0,0 +1,126 @@
+use chrono::prelude::*;
+use colored::Colorize;
+use std::io::Write;
+use std::path::Path;
+
+use crate::downloader::open_tick_lines;
+
+const PROGRESS_EVERY: u64 = 1_000_000;
+
+fn parse_leading_dt(line: &str) -> Option<DateTime<Utc>> {
+    let dt = line.split(',').next()?;
+    let dt = NaiveDateTime::parse_from_str(dt.trim(), "%Y-%m-%d %H:%M:%S%.f UTC").ok()?;
+    Some(DateTime::from_utc(dt, Utc))
+}
+
+// 输入文件(csv/csv.gz/bin均可, 按扩展名识别)已按时间排序，命中end即可停止扫描，避免把整个文件读入内存
+pub fn extract(
+    input: &Path,
+    output: &Path,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> std::io::Result<()> {
+    let lines = open_tick_lines(input)?;
+    let out = std::fs::File::create(output)?;
+    let mut writer = std::io::BufWriter::new(out);
+
+    let mut scanned: u64 = 0;
+    let mut written: u64 = 0;
+
+    for line in lines {
+        let line = line?;
+        scanned += 1;
+        if scanned % PROGRESS_EVERY == 0 {
+            println!("{} {} lines", "Scanned".yellow(), scanned);
+        }
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let dt = match parse_leading_dt(&line) {
+            Some(dt) => dt,
+            None => continue,
+        };
+
+        if dt < start {
+            continue;
+        }
+        if dt >= end {
+            break;
+        }
+
+        writeln!(writer, "{}", line)?;
+        written += 1;
+    }
+
+    writer.flush()?;
+    println!(
+        "{} {} rows, {} lines scanned",
+        "Written".yellow(),
+        written,
+        scanned
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_stops_at_end_and_keeps_range() {
+        let dir = std::env::temp_dir().join("dukascopy_range_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("ticks.csv");
+        let output = dir.join("ticks_out.csv");
+        std::fs::write(
+            &input,
+            "2020-01-01 09:00:00 UTC,1.1,1.0,1,1\n\
+             2020-01-01 10:00:00 UTC,1.2,1.1,1,1\n\
+             2020-01-01 11:00:00 UTC,1.3,1.2,1,1\n\
+             2020-01-01 12:00:00 UTC,1.4,1.3,1,1\n",
+        )
+        .unwrap();
+
+        let start = Utc.ymd(2020, 1, 1).and_hms(10, 0, 0);
+        let end = Utc.ymd(2020, 1, 1).and_hms(12, 0, 0);
+        extract(&input, &output, start, end).unwrap();
+
+        let content = std::fs::read_to_string(&output).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("2020-01-01 10:00:00 UTC"));
+        assert!(lines[1].starts_with("2020-01-01 11:00:00 UTC"));
+    }
+
+    #[test]
+    fn test_extract_decodes_csv_gz_input() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join("dukascopy_range_test_gz");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("ticks.csv.gz");
+        let output = dir.join("ticks_out.csv");
+
+        let mut encoder = GzEncoder::new(std::fs::File::create(&input).unwrap(), Compression::default());
+        encoder
+            .write_all(
+                b"2020-01-01 09:00:00 UTC,1.1,1.0,1,1\n\
+                  2020-01-01 10:00:00 UTC,1.2,1.1,1,1\n",
+            )
+            .unwrap();
+        encoder.finish().unwrap();
+
+        let start = Utc.ymd(2020, 1, 1).and_hms(10, 0, 0);
+        let end = Utc.ymd(2020, 1, 1).and_hms(12, 0, 0);
+        extract(&input, &output, start, end).unwrap();
+
+        let content = std::fs::read_to_string(&output).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("2020-01-01 10:00:00 UTC"));
+    }
+}